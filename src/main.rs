@@ -1,11 +1,14 @@
 use base64::engine::general_purpose;
 use base64::Engine;
 use flate2::read::GzDecoder;
+use rayon::prelude::*;
 use reqwest::{blocking::Client, Error};
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
+use sha1::Sha1;
 use sha2::{Digest, Sha512};
 use std::path::Path;
+use std::sync::Mutex;
 use std::{collections::HashMap, fs, io::Cursor};
 use tar::Archive;
 
@@ -14,6 +17,8 @@ struct PackageJSON {
     // name: String,
     // description: String,
     dependencies: Option<HashMap<String, String>>,
+    #[serde(rename = "devDependencies")]
+    dev_dependencies: Option<HashMap<String, String>>,
 }
 #[derive(Deserialize, Debug)]
 struct RegistryResponse {
@@ -25,8 +30,12 @@ struct RegistryVersionItem {
     version: String,
     dist: RegistryDist,
     dependencies: Option<HashMap<String, String>>,
-    // #[serde(rename = "devDependencies")]
-    // dev_dependencies: HashMap<String, String>,
+    // Transitive packages' own devDependencies are never installed (they're
+    // only relevant to that package's own test/build scripts), so this is
+    // parsed but intentionally unused beyond documenting that npm sends it.
+    #[serde(rename = "devDependencies")]
+    #[allow(dead_code)]
+    dev_dependencies: Option<HashMap<String, String>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -42,39 +51,169 @@ struct LockFileItem {
     integrity: String,
     dependencies: Option<HashMap<String, String>>,
 }
+/// Keyed by `"{name}@{version}"` so that distinct versions of the same
+/// package (hoisted vs. nested, after a version conflict) don't collide.
 type LockFile = HashMap<String, LockFileItem>;
 
-//TODO: Handle devDependencies
+/// `package-lock.json`, v1 through v3. v2/v3 carry an authoritative flat
+/// `packages` map keyed by install path; v1 only has the legacy nested
+/// `dependencies` tree.
+#[derive(Deserialize, Debug)]
+struct PackageLockFile {
+    #[serde(rename = "lockfileVersion")]
+    lockfile_version: u32,
+    packages: Option<HashMap<String, PackageLockPackageEntry>>,
+    dependencies: Option<HashMap<String, PackageLockDependencyEntry>>,
+}
 
-//TODO: Start with on demand dependency resolution, then switch to a different data structure.
-// Maybe a tree or a Directed Acylic Graph
+/// An entry in a v2/v3 `packages` map, keyed by its install path (e.g.
+/// `node_modules/foo/node_modules/bar`, or `""` for the root project).
+#[derive(Deserialize, Debug, Clone)]
+struct PackageLockPackageEntry {
+    version: Option<String>,
+    resolved: Option<String>,
+    integrity: Option<String>,
+    #[serde(default)]
+    dependencies: HashMap<String, String>,
+    #[serde(default)]
+    dev: bool,
+}
+
+/// An entry in the legacy v1 `dependencies` tree. Nested transitive deps
+/// live under `dependencies` on the entry itself rather than in a flat map.
+#[derive(Deserialize, Debug, Clone)]
+struct PackageLockDependencyEntry {
+    version: String,
+    resolved: Option<String>,
+    integrity: Option<String>,
+    #[serde(default)]
+    requires: HashMap<String, String>,
+    #[serde(default)]
+    dependencies: HashMap<String, PackageLockDependencyEntry>,
+    #[serde(default)]
+    dev: bool,
+}
 
 const LOCK_FILE_PATH: &str = "dep-lock.json";
 const PACKAGE_JSON_PATH: &str = "package.json";
+const PACKAGE_LOCK_PATH: &str = "package-lock.json";
+const CACHE_DIR: &str = "./.dep-cache";
+
+/// Content-addressable store path for a tarball keyed by its integrity hash,
+/// e.g. `sha512-abcd...`. The hash already uniquely identifies the bytes, so
+/// it doubles as the cache key.
+fn cache_path(integrity: &str) -> std::path::PathBuf {
+    let key = integrity.replace(['/', '+', '='], "_");
+    Path::new(CACHE_DIR).join(key)
+}
+
+/// Relative strength of a supported integrity algorithm, used to pick a
+/// winner when a dist entry carries more than one hash for the same tarball.
+fn algorithm_strength(algorithm: &str) -> Option<u8> {
+    match algorithm {
+        "sha512" => Some(1),
+        "sha1" => Some(0),
+        _ => None,
+    }
+}
+
+/// npm's `dist.integrity` (and SRI in general) can list several
+/// space-separated `algo-digest` hashes for the same artifact. Pick the
+/// strongest one we support rather than relying on whichever came first.
+fn strongest_integrity(integrity: &str) -> Option<String> {
+    integrity
+        .split_whitespace()
+        .filter(|hash| {
+            hash.split_once('-')
+                .is_some_and(|(algorithm, _)| algorithm_strength(algorithm).is_some())
+        })
+        .max_by_key(|hash| {
+            let (algorithm, _) = hash.split_once('-').expect("checked above");
+            algorithm_strength(algorithm).expect("checked above")
+        })
+        .map(str::to_string)
+}
+
+/// Whether devDependencies are installed for the root package. Only ever
+/// applies at the root: transitive packages' own devDependencies are never
+/// installed, regardless of mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstallMode {
+    WithDev,
+    ProductionOnly,
+}
+
+impl InstallMode {
+    fn from_args() -> InstallMode {
+        let production_only = std::env::args()
+            .any(|arg| arg == "--production" || arg == "--omit=dev");
+        if production_only {
+            InstallMode::ProductionOnly
+        } else {
+            InstallMode::WithDev
+        }
+    }
+
+    fn includes_dev(self) -> bool {
+        self == InstallMode::WithDev
+    }
+}
+
+/// Whether a lockfile entry marked `dev` should be installed under
+/// `install_mode`. devDependencies are only ever relevant at the root (a
+/// transitive package's own devDependencies are for its own build/test, not
+/// ours), so every entry a lockfile install path filters through here is
+/// necessarily non-root: `dev: true` always means "skip unless installing dev".
+fn should_install_locked_entry(install_mode: InstallMode, dev: bool) -> bool {
+    install_mode.includes_dev() || !dev
+}
 
 fn main() {
     let client = Client::new();
+    let install_mode = InstallMode::from_args();
     let package_json = fs::read_to_string(PACKAGE_JSON_PATH).expect("Error reading file");
 
     let package_json: PackageJSON =
         serde_json::from_str(&package_json).expect("Error reading json");
 
-    let mut lock_file: LockFile = if Path::new(LOCK_FILE_PATH).exists() {
+    // The previous run's lock file, consulted (read-only) so that a pinned
+    // version satisfying the current range is reused instead of always
+    // re-resolving to whatever the registry now considers "latest" for that
+    // range. The file we write back starts empty and is only ever populated
+    // with what this run actually installs, so a dependency that's dropped
+    // from the tree doesn't leave a stale row behind forever.
+    let old_lock_file: LockFile = if Path::new(LOCK_FILE_PATH).exists() {
         let lock_content = fs::read_to_string(LOCK_FILE_PATH).expect("Error reading lock file");
         serde_json::from_str(&lock_content).expect("Error parsing lock file")
     } else {
         HashMap::new()
     };
+    let lock_file: Mutex<LockFile> = Mutex::new(HashMap::new());
 
-    match package_json.dependencies {
-        Some(deps) => {
-            if let Err(e) = fetch_dependencies(deps, &client, &mut lock_file, None) {
-                eprintln!("Error: {e}")
+    if Path::new(PACKAGE_LOCK_PATH).exists() {
+        // A package-lock.json pins exact resolved URLs and integrity values,
+        // so we can install straight from it without re-querying the registry.
+        if let Err(e) = install_from_package_lock(PACKAGE_LOCK_PATH, &client, &lock_file, install_mode) {
+            eprintln!("Error installing from {PACKAGE_LOCK_PATH}: {e}")
+        }
+    } else {
+        let mut root_dependencies = package_json.dependencies.unwrap_or_default();
+        if install_mode.includes_dev() {
+            if let Some(dev_dependencies) = package_json.dev_dependencies {
+                root_dependencies.extend(dev_dependencies);
             }
         }
-        None => println!("No dependencies"),
+
+        if root_dependencies.is_empty() {
+            println!("No dependencies");
+        } else if let Err(e) =
+            install_from_registry(root_dependencies, &client, &old_lock_file, &lock_file)
+        {
+            eprintln!("Error: {e}")
+        }
     }
 
+    let lock_file = lock_file.into_inner().expect("Lock file mutex poisoned");
     if let Err(e) = write_lock_file(&lock_file) {
         eprintln!("Failed to write to lock file: {e}")
     }
@@ -87,86 +226,587 @@ fn write_lock_file(lock_file: &LockFile) -> Result<(), Box<dyn std::error::Error
     Ok(())
 }
 
-/// Fetches single dependency from registry
-fn fetch_single_dep(
-    name: &String,
-    version: &String,
+/// Installs directly from an existing `package-lock.json`, bypassing
+/// registry resolution entirely in favor of the lockfile's pinned
+/// `resolved`/`integrity` values.
+fn install_from_package_lock(
+    package_lock_path: &str,
     client: &Client,
-    lock_file: &mut LockFile,
-    parent_node_modules: Option<&String>, // Parent directory for nested `node_modules`
+    lock_file: &Mutex<LockFile>,
+    install_mode: InstallMode,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let dependency_folder = match parent_node_modules {
-        Some(parent) => format!("{}/node_modules", parent),
-        None => "./node_modules".to_string(),
+    let content = fs::read_to_string(package_lock_path)?;
+    let package_lock: PackageLockFile = serde_json::from_str(&content)?;
+
+    match package_lock.lockfile_version {
+        2 | 3 => {
+            let packages = package_lock.packages.unwrap_or_default();
+            install_from_packages_map(packages, client, lock_file, install_mode)
+        }
+        _ => {
+            let dependencies = package_lock.dependencies.unwrap_or_default();
+            install_from_dependency_tree(dependencies, client, lock_file, None, install_mode)
+        }
+    }
+}
+
+/// Installs every entry of a v2/v3 `packages` map. The map is already flat
+/// and keyed by install path, so entries can be fetched independently.
+fn install_from_packages_map(
+    packages: HashMap<String, PackageLockPackageEntry>,
+    client: &Client,
+    lock_file: &Mutex<LockFile>,
+    install_mode: InstallMode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    packages
+        .into_par_iter()
+        // The `""` key describes the root project itself, not a dependency to install.
+        .filter(|(path, _)| !path.is_empty())
+        .filter(|(_, entry)| should_install_locked_entry(install_mode, entry.dev))
+        .try_for_each(|(path, entry)| {
+            install_locked_package(&path, &entry, client, lock_file).map_err(|e| e.to_string())
+        })
+        .map_err(|e| e.into())
+}
+
+/// Splits a `packages` map key like `"node_modules/foo/node_modules/@scope/bar"`
+/// into its install directory and package name. A naive last-`/`-segment
+/// split would cut a scoped package's `@scope` off of its name, so the
+/// second-to-last segment is folded into the name whenever it's a scope.
+fn split_package_path(path: &str) -> (String, String) {
+    let segments: Vec<&str> = path.split('/').collect();
+    let name_segments = if segments.len() >= 2 && segments[segments.len() - 2].starts_with('@') {
+        2
+    } else {
+        1
     };
+    let split_at = segments.len() - name_segments;
 
-    // Check if dependency exists in the lock file
-    if let Some(lock_item) = lock_file.get(name) {
-        let package_version = VersionReq::parse(version)
-            .expect("Failed to parse dependency version from package.json");
+    let output_dir = segments[..split_at].join("/");
+    let output_dir = if output_dir.is_empty() { ".".to_string() } else { output_dir };
+    (output_dir, segments[split_at..].join("/"))
+}
 
-        let lock_version = Version::parse(&lock_item.version)
-            .expect("Failed to parse dependency version from dep-lock.json");
+fn install_locked_package(
+    path: &str,
+    entry: &PackageLockPackageEntry,
+    client: &Client,
+    lock_file: &Mutex<LockFile>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (output_dir, name) = split_package_path(path);
 
-        if package_version.matches(&lock_version) {
-            // Dependency already resolved and matches the required version
-            fetch_tarball(
-                &lock_item.resolved_url,
-                name,
-                client,
-                Some(lock_item.integrity.clone()),
-                &dependency_folder,
-            )?;
-            if let Some(deps) = &lock_item.dependencies {
-                fetch_dependencies(deps.clone(), client, lock_file, Some(&dependency_folder))?;
-            }
-            return Ok(());
-        }
+    let Some(resolved) = &entry.resolved else {
+        println!("Skipping {name}: no resolved tarball URL in lockfile");
+        return Ok(());
+    };
+
+    fetch_tarball(resolved, &name, client, entry.integrity.clone(), &output_dir)?;
+
+    if let Some(version) = &entry.version {
+        lock_file.lock().expect("Lock file mutex poisoned").insert(
+            format!("{name}@{version}"),
+            LockFileItem {
+                version: version.clone(),
+                resolved_url: resolved.clone(),
+                integrity: entry.integrity.clone().unwrap_or_default(),
+                dependencies: Some(entry.dependencies.clone()).filter(|deps| !deps.is_empty()),
+            },
+        );
     }
 
-    // Fetch the latest compatible version
-    let matched_dependency = get_latest_version(name, version, client)?;
-    println!("Matched: {:?}", matched_dependency);
+    Ok(())
+}
+
+/// Installs a v1 `dependencies` tree, recursing into each entry's own
+/// nested `dependencies` into a correspondingly nested `node_modules`.
+fn install_from_dependency_tree(
+    dependencies: HashMap<String, PackageLockDependencyEntry>,
+    client: &Client,
+    lock_file: &Mutex<LockFile>,
+    parent_node_modules: Option<&String>,
+    install_mode: InstallMode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    dependencies
+        .into_par_iter()
+        .filter(|(_, entry)| should_install_locked_entry(install_mode, entry.dev))
+        .try_for_each(|(name, entry)| {
+            install_locked_dependency(&name, &entry, client, lock_file, parent_node_modules, install_mode)
+                .map_err(|e| e.to_string())
+        })
+        .map_err(|e| e.into())
+}
+
+fn install_locked_dependency(
+    name: &str,
+    entry: &PackageLockDependencyEntry,
+    client: &Client,
+    lock_file: &Mutex<LockFile>,
+    parent_node_modules: Option<&String>,
+    install_mode: InstallMode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dependency_folder = match parent_node_modules {
+        Some(parent) => format!("{}/node_modules", parent),
+        None => "./node_modules".to_string(),
+    };
+
+    let Some(resolved) = &entry.resolved else {
+        println!("Skipping {name}: no resolved tarball URL in lockfile");
+        return Ok(());
+    };
 
-    let integrity = matched_dependency.dist.integrity.clone();
     fetch_tarball(
-        &matched_dependency.dist.tarball,
-        name,
+        resolved,
+        &name.to_string(),
         client,
-        Some(integrity.clone()),
+        entry.integrity.clone(),
         &dependency_folder,
     )?;
 
-    if let Some(deps) = &matched_dependency.dependencies {
-        fetch_dependencies(deps.clone(), client, lock_file, Some(&dependency_folder))?;
+    if !entry.dependencies.is_empty() {
+        let package_dir = format!("{dependency_folder}/{name}");
+        install_from_dependency_tree(
+            entry.dependencies.clone(),
+            client,
+            lock_file,
+            Some(&package_dir),
+            install_mode,
+        )?;
     }
 
-    // Update the lock file
-    lock_file.insert(
-        name.to_string(),
+    lock_file.lock().expect("Lock file mutex poisoned").insert(
+        format!("{name}@{}", entry.version),
         LockFileItem {
-            version: matched_dependency.version,
-            resolved_url: matched_dependency.dist.tarball,
-            integrity,
-            dependencies: matched_dependency.dependencies.clone(),
+            version: entry.version.clone(),
+            resolved_url: resolved.clone(),
+            integrity: entry.integrity.clone().unwrap_or_default(),
+            dependencies: Some(entry.requires.clone()).filter(|deps| !deps.is_empty()),
         },
     );
 
     Ok(())
 }
 
-fn fetch_dependencies(
+/// A dependency specifier as it appears in `package.json`/a lockfile: either
+/// a semver range to resolve against the npm registry, or a git reference
+/// (a `github:owner/repo#commit` shorthand or a `git(+https)://...#commit`
+/// URL) to resolve straight from the provider's commit tarball.
+enum DependencySpec {
+    Registry(VersionReq),
+    Git(GitDependency),
+}
+
+impl DependencySpec {
+    fn parse(version: &str) -> Result<DependencySpec, Box<dyn std::error::Error>> {
+        if let Some(shorthand) = version.strip_prefix("github:") {
+            return Ok(DependencySpec::Git(GitDependency::from_github_shorthand(
+                shorthand,
+            )?));
+        }
+        if version.starts_with("git+") || version.starts_with("git://") {
+            return Ok(DependencySpec::Git(GitDependency::from_git_url(version)?));
+        }
+        Ok(DependencySpec::Registry(VersionReq::parse(version)?))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GitDependency {
+    committish: String,
+    tarball_url: String,
+}
+
+impl GitDependency {
+    fn from_github_shorthand(shorthand: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (repo, committish) = shorthand.split_once('#').ok_or_else(|| {
+            format!("github: dependency '{shorthand}' is missing a pinned commit")
+        })?;
+        Ok(GitDependency {
+            committish: committish.to_string(),
+            tarball_url: format!("https://github.com/{repo}/archive/{committish}.tar.gz"),
+        })
+    }
+
+    fn from_git_url(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let url = url.strip_prefix("git+").unwrap_or(url);
+        let (base_url, committish) = url
+            .split_once('#')
+            .ok_or_else(|| format!("git dependency '{url}' is missing a pinned commit"))?;
+
+        // The nixpkgs-style providers (GitHub, GitLab, ...) each expose an
+        // automatic "archive at commit" tarball; only GitHub's is wired up here.
+        let repo_path = base_url
+            .split("github.com/")
+            .nth(1)
+            .ok_or_else(|| format!("Unsupported git host in '{base_url}'; only github.com is currently supported"))?
+            .trim_end_matches(".git");
+
+        Ok(GitDependency {
+            committish: committish.to_string(),
+            tarball_url: format!("https://github.com/{repo_path}/archive/{committish}.tar.gz"),
+        })
+    }
+}
+
+/// One occurrence of a dependency edge discovered while resolving the tree:
+/// `ancestors` is the chain of `(name, version)` pairs from the root down to
+/// (not including) this edge, which lets install time figure out exactly
+/// where a conflicting edge needs to nest even several levels deep. `name`
+/// and `version` are what was requested and resolved at this edge, and
+/// `dependencies` is what it itself requires (used both to keep resolving
+/// and to populate the lock file entry once installed).
+#[derive(Debug, Clone)]
+struct ResolvedEdge {
+    ancestors: Vec<(String, String)>,
+    name: String,
+    version: String,
+    resolved_url: String,
+    integrity: String, // empty for git dependencies, which aren't content-addressed
+    dependencies: HashMap<String, String>,
+}
+
+/// Resolution-phase memo: the first resolution of a given (name, specifier)
+/// pair is reused for every later occurrence, which saves registry
+/// round-trips. It does not by itself stop cycles: a memoized entry is still
+/// recursed into unless `is_cyclic` finds its name among the ancestors.
+type ResolutionMemo = Mutex<HashMap<(String, String), ResolvedEdge>>;
+
+/// Looks for an existing lock-file entry for `name` whose pinned version
+/// still satisfies `version_req`, so repeat installs with an unchanged
+/// `dep-lock.json` reuse that version (and its recorded dependencies)
+/// instead of drifting to whatever the registry now considers "latest
+/// matching" for the range. Hoisting can legitimately leave two different
+/// versions of the same package in the lock file (one hoisted, one nested
+/// for a conflict), so among every matching entry the highest version wins
+/// rather than whichever the `HashMap` happens to iterate to first.
+fn find_locked_match(
+    name: &str,
+    version_req: &VersionReq,
+    old_lock_file: &LockFile,
+) -> Option<(String, String, String, HashMap<String, String>)> {
+    let prefix = format!("{name}@");
+    old_lock_file
+        .iter()
+        .filter_map(|(key, item)| {
+            let version = Version::parse(key.strip_prefix(&prefix)?).ok()?;
+            version_req.matches(&version).then_some((version, item))
+        })
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, item)| {
+            (
+                item.version.clone(),
+                item.resolved_url.clone(),
+                item.integrity.clone(),
+                item.dependencies.clone().unwrap_or_default(),
+            )
+        })
+}
+
+/// Whether resolving `name` from here would re-enter a package already
+/// being resolved higher up the current chain — i.e. a genuine dependency
+/// cycle (`a` requires `b`, `b` requires `a`, etc).
+fn is_cyclic(ancestors: &[(String, String)], name: &str) -> bool {
+    ancestors.iter().any(|(ancestor_name, _)| ancestor_name == name)
+}
+
+/// Walks `dependencies` (and their transitive dependencies) to build the
+/// full set of resolved edges, without fetching or unpacking anything yet.
+/// This is deliberately separate from installation so hoisting can see the
+/// whole graph before any `node_modules` directory is touched.
+fn resolve_dependencies(
+    ancestors: &[(String, String)],
     dependencies: HashMap<String, String>,
     client: &Client,
-    lock_file: &mut LockFile,
-    parent_node_modules: Option<&String>, // Parent directory for nested `node_modules`
+    old_lock_file: &LockFile,
+    memo: &ResolutionMemo,
+    edges: &Mutex<Vec<ResolvedEdge>>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    for (name, version) in dependencies {
-        fetch_single_dep(&name, &version, client, lock_file, parent_node_modules)?;
+    dependencies
+        .into_par_iter()
+        .try_for_each(|(name, specifier)| {
+            resolve_single_dependency(ancestors, &name, &specifier, client, old_lock_file, memo, edges)
+                .map_err(|e| e.to_string())
+        })
+        .map_err(|e| e.into())
+}
+
+fn resolve_single_dependency(
+    ancestors: &[(String, String)],
+    name: &String,
+    specifier: &String,
+    client: &Client,
+    old_lock_file: &LockFile,
+    memo: &ResolutionMemo,
+    edges: &Mutex<Vec<ResolvedEdge>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let key = (name.clone(), specifier.clone());
+    // `name` already appearing somewhere above us in the chain means
+    // resolving it again would just walk the same cycle forever (e.g.
+    // `a` requires `b`, `b` requires `a`): record the edge so hoisting and
+    // install still see this occurrence, but don't recurse into it again.
+    let is_cycle = is_cyclic(ancestors, name);
+
+    // Dropping the guard before recursing (rather than keeping it held across
+    // the `if let`) avoids re-locking `memo` from within `resolve_dependencies`.
+    let memoized = memo.lock().expect("Resolution memo mutex poisoned").get(&key).cloned();
+    if let Some(memoized) = memoized {
+        edges.lock().expect("Edge list mutex poisoned").push(ResolvedEdge {
+            ancestors: ancestors.to_vec(),
+            ..memoized.clone()
+        });
+        if !is_cycle && !memoized.dependencies.is_empty() {
+            let mut child_ancestors = ancestors.to_vec();
+            child_ancestors.push((name.clone(), memoized.version.clone()));
+            return resolve_dependencies(
+                &child_ancestors,
+                memoized.dependencies,
+                client,
+                old_lock_file,
+                memo,
+                edges,
+            );
+        }
+        return Ok(());
+    }
+
+    let (template, nested_dependencies) = match DependencySpec::parse(specifier)? {
+        DependencySpec::Git(git_dep) => {
+            let template = ResolvedEdge {
+                ancestors: Vec::new(),
+                name: name.clone(),
+                version: git_dep.committish,
+                resolved_url: git_dep.tarball_url,
+                integrity: String::new(),
+                dependencies: HashMap::new(),
+            };
+            (template, HashMap::new())
+        }
+        DependencySpec::Registry(version_req) => {
+            if let Some((locked_version, resolved_url, integrity, nested_dependencies)) =
+                find_locked_match(name, &version_req, old_lock_file)
+            {
+                let template = ResolvedEdge {
+                    ancestors: Vec::new(),
+                    name: name.clone(),
+                    version: locked_version,
+                    resolved_url,
+                    integrity,
+                    dependencies: nested_dependencies.clone(),
+                };
+                (template, nested_dependencies)
+            } else {
+                let matched = get_latest_version(name, specifier, client)?;
+                println!("Matched: {:?}", matched);
+
+                // `dist.integrity` may list several algorithms for the same
+                // tarball; only ever record and verify against the strongest
+                // one we support.
+                let integrity = strongest_integrity(&matched.dist.integrity).ok_or_else(|| {
+                    format!("No supported integrity algorithm in {}", matched.dist.integrity)
+                })?;
+                let nested_dependencies = matched.dependencies.clone().unwrap_or_default();
+                let template = ResolvedEdge {
+                    ancestors: Vec::new(),
+                    name: name.clone(),
+                    version: matched.version,
+                    resolved_url: matched.dist.tarball,
+                    integrity,
+                    dependencies: nested_dependencies.clone(),
+                };
+                (template, nested_dependencies)
+            }
+        }
+    };
+
+    memo.lock()
+        .expect("Resolution memo mutex poisoned")
+        .insert(key, template.clone());
+    edges.lock().expect("Edge list mutex poisoned").push(ResolvedEdge {
+        ancestors: ancestors.to_vec(),
+        ..template.clone()
+    });
+
+    if !is_cycle && !nested_dependencies.is_empty() {
+        let mut child_ancestors = ancestors.to_vec();
+        child_ancestors.push((name.clone(), template.version));
+        resolve_dependencies(&child_ancestors, nested_dependencies, client, old_lock_file, memo, edges)?;
+    }
+
+    Ok(())
+}
+
+/// Ranks a version string for deterministic tie-breaking: a valid semver
+/// version always outranks a non-semver ref (e.g. a git commit hash), and
+/// within the same kind ties fall back to the raw string. Used so a tied
+/// hoist decision depends only on the edges themselves, never on the order
+/// the parallel resolver happened to push them in.
+fn version_rank(version: &str) -> (u8, Option<Version>, &str) {
+    match Version::parse(version) {
+        Ok(parsed) => (1, Some(parsed), version),
+        Err(_) => (0, None, version),
+    }
+}
+
+/// Collapses a resolved edge list into a hoisted layout: every package name
+/// gets a single winning version (the root's own requested version if it
+/// requests one directly, otherwise whichever version the most edges agree
+/// on) that is installed once into the shared `./node_modules`. Edges that
+/// disagree with the winning version are genuine conflicts and are nested
+/// instead. Also returns the winning version per name, which installation
+/// needs to resolve nesting for conflicts more than one level deep.
+fn hoist_dependency_graph(
+    edges: Vec<ResolvedEdge>,
+) -> (Vec<ResolvedEdge>, Vec<ResolvedEdge>, HashMap<String, String>) {
+    struct VersionTally {
+        version: String,
+        count: usize,
+        from_root: bool,
+    }
+
+    let mut version_counts: HashMap<(&str, &str), usize> = HashMap::new();
+    for edge in &edges {
+        *version_counts
+            .entry((edge.name.as_str(), edge.version.as_str()))
+            .or_insert(0) += 1;
     }
+
+    let mut winners: HashMap<String, VersionTally> = HashMap::new();
+    for edge in &edges {
+        let count = version_counts[&(edge.name.as_str(), edge.version.as_str())];
+        let from_root = edge.ancestors.is_empty();
+        winners
+            .entry(edge.name.clone())
+            .and_modify(|current| {
+                let edge_wins = if from_root != current.from_root {
+                    from_root
+                } else if count != current.count {
+                    count > current.count
+                } else {
+                    version_rank(&edge.version) > version_rank(&current.version)
+                };
+                if edge_wins {
+                    current.version = edge.version.clone();
+                    current.count = count;
+                    current.from_root = from_root;
+                }
+            })
+            .or_insert(VersionTally {
+                version: edge.version.clone(),
+                count,
+                from_root,
+            });
+    }
+
+    let winning_versions: HashMap<String, String> = winners
+        .iter()
+        .map(|(name, tally)| (name.clone(), tally.version.clone()))
+        .collect();
+
+    let mut hoisted: HashMap<String, ResolvedEdge> = HashMap::new();
+    let mut nested = Vec::new();
+    for edge in edges {
+        if winning_versions[&edge.name] == edge.version {
+            hoisted.entry(edge.name.clone()).or_insert(edge);
+        } else {
+            nested.push(edge);
+        }
+    }
+
+    (hoisted.into_values().collect(), nested, winning_versions)
+}
+
+/// Finds where a nested edge actually needs to live by walking its ancestor
+/// chain from the root: a hoisted ancestor resets the path back to the
+/// shared `./node_modules` (that's where a hoisted package always lives,
+/// regardless of who required it), while a conflicting ancestor nests one
+/// level deeper under whatever path was accumulated so far. This handles
+/// version conflicts more than one level deep correctly, instead of assuming
+/// every nested edge's immediate parent was itself hoisted to the top level.
+fn compute_install_dir(ancestors: &[(String, String)], winning_versions: &HashMap<String, String>) -> String {
+    let mut dir = "./node_modules".to_string();
+    for (ancestor_name, ancestor_version) in ancestors {
+        let ancestor_hoisted = winning_versions
+            .get(ancestor_name)
+            .is_some_and(|version| version == ancestor_version);
+        dir = if ancestor_hoisted {
+            format!("./node_modules/{ancestor_name}/node_modules")
+        } else {
+            format!("{dir}/{ancestor_name}/node_modules")
+        };
+    }
+    dir
+}
+
+/// Fetches and unpacks a resolved graph: hoisted packages land in the shared
+/// `./node_modules`, and conflicting versions nest under wherever their own
+/// ancestor chain actually resolves to.
+fn install_resolved_graph(
+    hoisted: Vec<ResolvedEdge>,
+    nested: Vec<ResolvedEdge>,
+    winning_versions: &HashMap<String, String>,
+    client: &Client,
+    lock_file: &Mutex<LockFile>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    hoisted
+        .into_par_iter()
+        .try_for_each(|edge| {
+            install_resolved_edge(&edge, client, lock_file, "./node_modules".to_string())
+                .map_err(|e| e.to_string())
+        })
+        .map_err(|e: String| -> Box<dyn std::error::Error> { e.into() })?;
+
+    nested
+        .into_par_iter()
+        .try_for_each(|edge| {
+            let output_dir = compute_install_dir(&edge.ancestors, winning_versions);
+            install_resolved_edge(&edge, client, lock_file, output_dir).map_err(|e| e.to_string())
+        })
+        .map_err(|e| e.into())
+}
+
+fn install_resolved_edge(
+    edge: &ResolvedEdge,
+    client: &Client,
+    lock_file: &Mutex<LockFile>,
+    output_dir: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let integrity = if edge.integrity.is_empty() {
+        None
+    } else {
+        Some(edge.integrity.clone())
+    };
+    fetch_tarball(&edge.resolved_url, &edge.name, client, integrity, &output_dir)?;
+
+    lock_file.lock().expect("Lock file mutex poisoned").insert(
+        format!("{}@{}", edge.name, edge.version),
+        LockFileItem {
+            version: edge.version.clone(),
+            resolved_url: edge.resolved_url.clone(),
+            integrity: edge.integrity.clone(),
+            dependencies: Some(edge.dependencies.clone()).filter(|deps| !deps.is_empty()),
+        },
+    );
+
     Ok(())
 }
 
+/// Resolves `dependencies` into a full graph and installs it with hoisting,
+/// replacing the old approach of fetching and recursing one dependency at a
+/// time into ever-deeper nested `node_modules`.
+fn install_from_registry(
+    dependencies: HashMap<String, String>,
+    client: &Client,
+    old_lock_file: &LockFile,
+    lock_file: &Mutex<LockFile>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let memo: ResolutionMemo = Mutex::new(HashMap::new());
+    let edges = Mutex::new(Vec::new());
+    resolve_dependencies(&[], dependencies, client, old_lock_file, &memo, &edges)?;
+
+    let (hoisted, nested, winning_versions) =
+        hoist_dependency_graph(edges.into_inner().expect("Edge list mutex poisoned"));
+    install_resolved_graph(hoisted, nested, &winning_versions, client, lock_file)
+}
+
 fn fetch_tarball(
     url: &String,
     name: &String,
@@ -174,19 +814,40 @@ fn fetch_tarball(
     expected_integrity: Option<String>,
     output_dir: &String, // Directory where the dependency will be installed
 ) -> Result<(), Box<dyn std::error::Error>> {
+    // A cache hit already has verified bytes sitting on disk under their
+    // integrity key, so we can skip the network round-trip and re-hashing.
+    if let Some(expected_hash) = &expected_integrity {
+        let cached = cache_path(expected_hash);
+        if cached.exists() {
+            println!("Cache hit for {name} ({expected_hash})");
+            let content_bytes = fs::read(&cached)?;
+            return unpack_tarball(&content_bytes, name, output_dir);
+        }
+    }
+
     let response = client.get(url).send()?;
     let content_bytes = response.bytes()?;
 
-    if let Some(expected_hash) = expected_integrity {
+    if let Some(expected_hash) = &expected_integrity {
         let parts: Vec<&str> = expected_hash.split('-').collect();
-        if parts.len() != 2 || parts[0] != "sha512" {
-            return Err(format!("Unsupported hash algorithm in {expected_hash}").into());
+        if parts.len() != 2 {
+            return Err(format!("Malformed integrity value {expected_hash}").into());
         }
-        let expected_hash_value = parts[1];
+        let (algorithm, expected_hash_value) = (parts[0], parts[1]);
 
-        let mut hasher = Sha512::new();
-        hasher.update(&content_bytes);
-        let computed_hash = general_purpose::STANDARD.encode(hasher.finalize());
+        let computed_hash = match algorithm {
+            "sha512" => {
+                let mut hasher = Sha512::new();
+                hasher.update(&content_bytes);
+                general_purpose::STANDARD.encode(hasher.finalize())
+            }
+            "sha1" => {
+                let mut hasher = Sha1::new();
+                hasher.update(&content_bytes);
+                general_purpose::STANDARD.encode(hasher.finalize())
+            }
+            _ => return Err(format!("Unsupported hash algorithm in {expected_hash}").into()),
+        };
 
         if expected_hash_value != computed_hash {
             return Err(format!(
@@ -195,10 +856,22 @@ fn fetch_tarball(
             .into());
         }
         println!("Integrity check passed for {name}");
+
+        fs::create_dir_all(CACHE_DIR)?;
+        fs::write(cache_path(expected_hash), &content_bytes)?;
     } else {
         println!("No integrity hash provided for {name}. Skipping validation.");
     }
 
+    unpack_tarball(&content_bytes, name, output_dir)
+}
+
+/// Unpacks a downloaded (or cached) gzipped tarball into `output_dir/name`.
+fn unpack_tarball(
+    content_bytes: &[u8],
+    name: &String,
+    output_dir: &String,
+) -> Result<(), Box<dyn std::error::Error>> {
     let cursor = Cursor::new(&content_bytes);
     let tar = GzDecoder::new(cursor);
     let mut archive = Archive::new(tar);
@@ -255,3 +928,85 @@ fn get_latest_version(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edge(ancestors: &[(&str, &str)], name: &str, version: &str) -> ResolvedEdge {
+        ResolvedEdge {
+            ancestors: ancestors
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect(),
+            name: name.to_string(),
+            version: version.to_string(),
+            resolved_url: format!("https://example.com/{name}-{version}.tgz"),
+            integrity: String::new(),
+            dependencies: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn is_cyclic_detects_a_repeated_ancestor() {
+        let ancestors = [("a".to_string(), "1.0.0".to_string())];
+        assert!(is_cyclic(&ancestors, "a"));
+        assert!(!is_cyclic(&ancestors, "b"));
+        assert!(!is_cyclic(&[], "a"));
+    }
+
+    #[test]
+    fn hoist_resolves_conflicts_more_than_one_level_deep() {
+        let edges = vec![
+            edge(&[], "a", "1.0.0"),
+            edge(&[], "c", "2.0.0"),
+            edge(&[("a", "1.0.0")], "c", "1.0.0"),
+            edge(&[], "d", "2.0.0"),
+            edge(&[("a", "1.0.0"), ("c", "1.0.0")], "d", "1.0.0"),
+        ];
+
+        let (hoisted, nested, winning_versions) = hoist_dependency_graph(edges);
+
+        assert_eq!(winning_versions.get("a").map(String::as_str), Some("1.0.0"));
+        assert_eq!(winning_versions.get("c").map(String::as_str), Some("2.0.0"));
+        assert_eq!(winning_versions.get("d").map(String::as_str), Some("2.0.0"));
+        assert_eq!(hoisted.len(), 3);
+        assert_eq!(nested.len(), 2);
+
+        let nested_c = nested
+            .iter()
+            .find(|edge| edge.name == "c" && edge.version == "1.0.0")
+            .expect("c@1.0.0 should be nested");
+        assert_eq!(
+            compute_install_dir(&nested_c.ancestors, &winning_versions),
+            "./node_modules/a/node_modules"
+        );
+
+        // d@1.0.0 is required by c@1.0.0, which is itself nested under a/ -
+        // so d must nest one level deeper still, not under the top-level c/.
+        let nested_d = nested
+            .iter()
+            .find(|edge| edge.name == "d" && edge.version == "1.0.0")
+            .expect("d@1.0.0 should be nested");
+        assert_eq!(
+            compute_install_dir(&nested_d.ancestors, &winning_versions),
+            "./node_modules/a/node_modules/c/node_modules"
+        );
+    }
+
+    #[test]
+    fn split_package_path_keeps_scope_joined_to_name() {
+        assert_eq!(
+            split_package_path("node_modules/@babel/core"),
+            ("node_modules".to_string(), "@babel/core".to_string())
+        );
+        assert_eq!(
+            split_package_path("node_modules/foo/node_modules/@scope/bar"),
+            ("node_modules/foo/node_modules".to_string(), "@scope/bar".to_string())
+        );
+        assert_eq!(
+            split_package_path("node_modules/lodash"),
+            ("node_modules".to_string(), "lodash".to_string())
+        );
+    }
+}